@@ -0,0 +1,270 @@
+//! Clique-style authority-round primitives shared between [`crate::poa_miner::PoaMiner`] and
+//! [`crate::poa_follower::PoaFollower`].
+//!
+//! Blocks are sealed by signing the header with one of a fixed set of authority keys. The
+//! signature is stored Clique-style inside `extra_data`: a 32-byte vanity prefix followed by a
+//! 65-byte recoverable secp256k1 signature over the RLP hash of the header with that signature
+//! portion zeroed out.
+
+use std::collections::{HashSet, VecDeque};
+
+use alloy_primitives::{keccak256, Address, Bytes, B256};
+use alloy_rlp::Encodable;
+use reth_ethereum::primitives::Header;
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, PublicKey, Secp256k1, SecretKey,
+};
+
+/// Length in bytes of the vanity prefix that precedes the signature in `extra_data`.
+pub const VANITY_LEN: usize = 32;
+/// Length in bytes of the recoverable secp256k1 signature appended to `extra_data`.
+pub const SIGNATURE_LEN: usize = 65;
+
+/// The fixed set of authorities allowed to seal blocks, in round-robin order.
+#[derive(Debug, Clone)]
+pub struct AuthoritySet {
+    authorities: Vec<Address>,
+}
+
+impl AuthoritySet {
+    pub fn new(authorities: Vec<Address>) -> Self {
+        assert!(!authorities.is_empty(), "authority set cannot be empty");
+        Self { authorities }
+    }
+
+    /// Number of authorities, `N`.
+    pub fn len(&self) -> usize {
+        self.authorities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.authorities.is_empty()
+    }
+
+    /// Returns the authority whose turn it is to seal `block_number`.
+    pub fn in_turn_signer(&self, block_number: u64) -> Address {
+        self.authorities[(block_number % self.authorities.len() as u64) as usize]
+    }
+
+    /// Returns `true` if `signer` is a member of this authority set.
+    pub fn contains(&self, signer: &Address) -> bool {
+        self.authorities.contains(signer)
+    }
+
+    /// Number of the most recent signers that must be distinct from a given signer before it may
+    /// seal again: `floor(N/2)`. This is the forbidden window, not a block count — a signer may
+    /// reappear once this many *other* blocks have been sealed since, which is exactly the
+    /// honest round-robin period for any `N`. One more than this (as in real Clique's
+    /// `gap < limit` check) would make the window as wide as the round-robin cycle itself and
+    /// permanently reject every signer from the cycle's last slot onward for `N <= 2`.
+    pub fn signer_cooldown(&self) -> usize {
+        self.authorities.len() / 2
+    }
+
+    /// Difficulty to use when sealing `block_number` as `signer`: `2` if in-turn, `1` otherwise.
+    pub fn difficulty_for(&self, block_number: u64, signer: &Address) -> u64 {
+        if self.in_turn_signer(block_number) == *signer {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Given recently sealed blocks (oldest first, as `(hash, signer)` pairs), returns
+    /// `(head, safe, finalized)`: `head` is the newest block, `safe` is the newest block with at
+    /// least one distinct authority having sealed on top of it, and `finalized` is the newest
+    /// block with strictly more than `N/2` distinct authorities having sealed on top of it.
+    /// Falls back to `head` for `safe`/`finalized` when there isn't enough history yet.
+    pub fn finality_state(&self, sealed: &VecDeque<(B256, Address)>) -> (B256, B256, B256) {
+        let head = sealed.back().expect("at least 1 block exists").0;
+        let finalized_threshold = self.len() / 2 + 1;
+
+        let mut descendant_signers = HashSet::new();
+        let mut safe = head;
+        let mut finalized = head;
+        let mut safe_found = false;
+        let mut finalized_found = false;
+
+        for (hash, signer) in sealed.iter().rev() {
+            if !safe_found && descendant_signers.len() >= 1 {
+                safe = *hash;
+                safe_found = true;
+            }
+            if !finalized_found && descendant_signers.len() >= finalized_threshold {
+                finalized = *hash;
+                finalized_found = true;
+            }
+            if safe_found && finalized_found {
+                break;
+            }
+            descendant_signers.insert(*signer);
+        }
+
+        (head, safe, finalized)
+    }
+}
+
+/// Returns the hash that gets signed/recovered for a sealed header: the RLP hash of `header`
+/// with the trailing [`SIGNATURE_LEN`] bytes of `extra_data` zeroed out.
+pub fn signing_hash(header: &Header) -> B256 {
+    let mut unsigned = header.clone();
+    let mut extra = unsigned.extra_data.to_vec();
+    extra.resize(VANITY_LEN + SIGNATURE_LEN, 0);
+    extra[VANITY_LEN..].fill(0);
+    unsigned.extra_data = Bytes::from(extra);
+
+    let mut buf = Vec::new();
+    unsigned.encode(&mut buf);
+    keccak256(&buf)
+}
+
+/// Signs `header` in place with `key`, writing the Clique-style `extra_data` (vanity preserved,
+/// signature replaced).
+pub fn sign_header(header: &mut Header, key: &SecretKey) {
+    let hash = signing_hash(header);
+    let secp = Secp256k1::signing_only();
+    let message = Message::from_digest(hash.0);
+    let signature = secp.sign_ecdsa_recoverable(&message, key);
+    let (recovery_id, data) = signature.serialize_compact();
+
+    let mut extra = header.extra_data.to_vec();
+    extra.resize(VANITY_LEN, 0);
+    extra.extend_from_slice(&data);
+    extra.push(recovery_id.to_i32() as u8);
+    header.extra_data = Bytes::from(extra);
+}
+
+/// Recovers the address that signed `header`, per the `extra_data` convention above.
+pub fn recover_signer(header: &Header) -> eyre::Result<Address> {
+    let extra = &header.extra_data;
+    eyre::ensure!(
+        extra.len() == VANITY_LEN + SIGNATURE_LEN,
+        "extra_data has unexpected length {} (expected {})",
+        extra.len(),
+        VANITY_LEN + SIGNATURE_LEN
+    );
+
+    let sig_bytes = &extra[VANITY_LEN..VANITY_LEN + 64];
+    let recovery_id = RecoveryId::from_i32(extra[VANITY_LEN + 64] as i32)?;
+    let signature = RecoverableSignature::from_compact(sig_bytes, recovery_id)?;
+
+    let hash = signing_hash(header);
+    let message = Message::from_digest(hash.0);
+    let secp = Secp256k1::verification_only();
+    let public_key = secp.recover_ecdsa(&message, &signature)?;
+
+    Ok(public_key_to_address(&public_key))
+}
+
+fn public_key_to_address(public_key: &PublicKey) -> Address {
+    let uncompressed = public_key.serialize_uncompressed();
+    // Skip the leading 0x04 tag; the address is the last 20 bytes of keccak256(pubkey_xy).
+    let hash = keccak256(&uncompressed[1..]);
+    Address::from_slice(&hash[12..])
+}
+
+/// Derives the signer address corresponding to a secp256k1 secret key.
+pub fn address_from_secret_key(key: &SecretKey) -> Address {
+    let public_key = PublicKey::from_secret_key(&Secp256k1::new(), key);
+    public_key_to_address(&public_key)
+}
+
+/// Builds the initial `extra_data` value for a header about to be sealed: `vanity` bytes
+/// followed by a zeroed signature slot, ready for [`sign_header`].
+pub fn empty_extra_data(vanity: &[u8]) -> Bytes {
+    let mut extra = vanity.to_vec();
+    extra.resize(VANITY_LEN + SIGNATURE_LEN, 0);
+    Bytes::from(extra)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signer() -> (SecretKey, Address) {
+        let key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        let address = address_from_secret_key(&key);
+        (key, address)
+    }
+
+    #[test]
+    fn sign_and_recover_roundtrip() {
+        let (key, address) = signer();
+        let mut header = Header::default();
+        header.extra_data = empty_extra_data(b"reth-poa-example");
+
+        sign_header(&mut header, &key);
+
+        assert_eq!(recover_signer(&header).unwrap(), address);
+    }
+
+    #[test]
+    fn recover_yields_different_signer_on_tampered_header() {
+        let (key, _) = signer();
+        let mut header = Header::default();
+        header.extra_data = empty_extra_data(b"reth-poa-example");
+        sign_header(&mut header, &key);
+
+        // Mutating the header after signing changes its signing hash, so the recovered address
+        // no longer matches the original signer.
+        header.number += 1;
+
+        assert_ne!(recover_signer(&header).unwrap(), address_from_secret_key(&key));
+    }
+
+    #[test]
+    fn recover_fails_on_wrong_length_extra_data() {
+        let header = Header::default();
+
+        assert!(recover_signer(&header).is_err());
+    }
+
+    #[test]
+    fn signer_cooldown_is_smaller_than_the_round_robin_period() {
+        for n in 1..=8usize {
+            let authorities = AuthoritySet::new((0..n as u8).map(addr).collect());
+            // The forbidden window must never reach a full round-robin period, or a signer whose
+            // turn comes back around gets permanently rejected as "recently signed".
+            assert!(authorities.signer_cooldown() < n);
+        }
+    }
+
+    fn addr(n: u8) -> Address {
+        Address::repeat_byte(n)
+    }
+
+    fn hash(n: u8) -> B256 {
+        B256::repeat_byte(n)
+    }
+
+    #[test]
+    fn finality_state_falls_back_to_head_with_no_history() {
+        let authorities = AuthoritySet::new(vec![addr(0), addr(1), addr(2)]);
+        let sealed = VecDeque::from([(hash(1), addr(0))]);
+
+        assert_eq!(authorities.finality_state(&sealed), (hash(1), hash(1), hash(1)));
+    }
+
+    #[test]
+    fn finality_state_falls_back_to_head_for_finalized_only() {
+        // N=3 needs 2 distinct descendant signers to finalize; only 1 is available here.
+        let authorities = AuthoritySet::new(vec![addr(0), addr(1), addr(2)]);
+        let sealed = VecDeque::from([(hash(1), addr(0)), (hash(2), addr(1))]);
+
+        assert_eq!(authorities.finality_state(&sealed), (hash(2), hash(1), hash(2)));
+    }
+
+    #[test]
+    fn finality_state_derives_safe_and_finalized_from_confirmations() {
+        let authorities = AuthoritySet::new(vec![addr(0), addr(1), addr(2)]);
+        let sealed = VecDeque::from([
+            (hash(1), addr(0)),
+            (hash(2), addr(1)),
+            (hash(3), addr(2)),
+            (hash(4), addr(0)),
+        ]);
+
+        assert_eq!(authorities.finality_state(&sealed), (hash(4), hash(3), hash(2)));
+    }
+}
@@ -0,0 +1,129 @@
+//! Consensus override that accepts Clique-style sealed headers.
+//!
+//! Stock post-merge (EIP-3675) header validation caps `extra_data` at 32 bytes, but
+//! [`crate::authority`]'s Clique-style sealing stores a 32-byte vanity prefix followed by a
+//! 65-byte recoverable signature — 97 bytes total. Without this override, every block
+//! [`crate::poa_miner::PoaMiner`] seals comes back `Invalid` from `new_payload` and the chain
+//! never produces a single accepted block. [`PoaConsensus`] wraps the stock Ethereum consensus
+//! implementation and relaxes only that one check; everything else (timestamp, gas limit, body
+//! matches header, state root, receipts, ...) is delegated unchanged.
+
+use std::sync::Arc;
+
+use reth_ethereum::{
+    chainspec::ChainSpec,
+    consensus::{Consensus, ConsensusError, EthBeaconConsensus, FullConsensus, HeaderValidator},
+    node::{
+        api::{FullNodeTypes, NodeTypes},
+        builder::{components::ConsensusBuilder, BuilderContext},
+    },
+    primitives::EthPrimitives,
+};
+
+use crate::authority;
+
+/// Wraps [`EthBeaconConsensus`], accepting the 97-byte Clique-style `extra_data` that stock
+/// validation would otherwise reject as exceeding the 32-byte post-merge maximum.
+#[derive(Debug, Clone)]
+pub struct PoaConsensus {
+    inner: EthBeaconConsensus<ChainSpec>,
+}
+
+impl PoaConsensus {
+    pub fn new(chain_spec: Arc<ChainSpec>) -> Self {
+        Self { inner: EthBeaconConsensus::new(chain_spec) }
+    }
+
+    /// Returns `true` for the one error the inner consensus raises purely because of our
+    /// Clique-style `extra_data`, which is safe to wave through since [`authority::recover_signer`]
+    /// independently checks the same length before trusting the signature it contains.
+    fn is_clique_extra_data(error: &ConsensusError) -> bool {
+        matches!(
+            error,
+            ConsensusError::ExtraDataExceedsMax { len }
+                if *len == authority::VANITY_LEN + authority::SIGNATURE_LEN
+        )
+    }
+}
+
+impl<H> HeaderValidator<H> for PoaConsensus
+where
+    EthBeaconConsensus<ChainSpec>: HeaderValidator<H>,
+{
+    fn validate_header(
+        &self,
+        header: &reth_ethereum::primitives::SealedHeader<H>,
+    ) -> Result<(), ConsensusError> {
+        match self.inner.validate_header(header) {
+            Err(error) if Self::is_clique_extra_data(&error) => Ok(()),
+            result => result,
+        }
+    }
+
+    fn validate_header_against_parent(
+        &self,
+        header: &reth_ethereum::primitives::SealedHeader<H>,
+        parent: &reth_ethereum::primitives::SealedHeader<H>,
+    ) -> Result<(), ConsensusError> {
+        match self.inner.validate_header_against_parent(header, parent) {
+            Err(error) if Self::is_clique_extra_data(&error) => Ok(()),
+            result => result,
+        }
+    }
+}
+
+impl<B> Consensus<B> for PoaConsensus
+where
+    EthBeaconConsensus<ChainSpec>: Consensus<B>,
+    B: reth_ethereum::primitives::Block,
+{
+    type Error = ConsensusError;
+
+    fn validate_body_against_header(
+        &self,
+        body: &B::Body,
+        header: &reth_ethereum::primitives::SealedHeader<B::Header>,
+    ) -> Result<(), Self::Error> {
+        self.inner.validate_body_against_header(body, header)
+    }
+
+    fn validate_block_pre_execution(
+        &self,
+        block: &reth_ethereum::primitives::SealedBlock<B>,
+    ) -> Result<(), Self::Error> {
+        self.inner.validate_block_pre_execution(block)
+    }
+}
+
+impl<N> FullConsensus<N> for PoaConsensus
+where
+    EthBeaconConsensus<ChainSpec>: FullConsensus<N>,
+    N: reth_ethereum::primitives::NodePrimitives,
+{
+    fn validate_block_post_execution(
+        &self,
+        block: &reth_ethereum::primitives::RecoveredBlock<N::Block>,
+        result: &reth_ethereum::provider::BlockExecutionResult<N::Receipt>,
+    ) -> Result<(), ConsensusError> {
+        self.inner.validate_block_post_execution(block, result)
+    }
+}
+
+/// Installs [`PoaConsensus`] in place of the stock [`EthereumConsensusBuilder`] so sealed Clique
+/// blocks survive `new_payload`.
+///
+/// [`EthereumConsensusBuilder`]: reth_ethereum::node::EthereumConsensusBuilder
+#[derive(Debug, Default, Clone)]
+#[non_exhaustive]
+pub struct PoaConsensusBuilder;
+
+impl<Node> ConsensusBuilder<Node> for PoaConsensusBuilder
+where
+    Node: FullNodeTypes<Types: NodeTypes<ChainSpec = ChainSpec, Primitives = EthPrimitives>>,
+{
+    type Consensus = Arc<PoaConsensus>;
+
+    async fn build_consensus(self, ctx: &BuilderContext<Node>) -> eyre::Result<Self::Consensus> {
+        Ok(Arc::new(PoaConsensus::new(ctx.chain_spec())))
+    }
+}
@@ -0,0 +1,196 @@
+//! Hardfork-aware Engine API helpers.
+//!
+//! The Engine API's `new_payload`/`fork_choice_updated` calls gained new versions (and new
+//! payload-attribute fields) at Shanghai and Cancun. [`ForkResolver`] picks the right version for
+//! a given block timestamp, and [`ForkAwarePayloadAttributesBuilder`] fills in the
+//! fork-conditional attribute fields an inner builder doesn't already set.
+
+use std::sync::Arc;
+
+use alloy_eips::eip4895::Withdrawal;
+use alloy_primitives::B256;
+use reth_ethereum::{
+    chainspec::{ChainSpec, EthereumHardforks},
+    node::api::{EngineApiMessageVersion, PayloadAttributesBuilder},
+    rpc::types::engine::PayloadAttributes as EthPayloadAttributes,
+};
+
+/// Resolves which Engine API message version applies to a block built at a given timestamp.
+#[derive(Debug, Clone)]
+pub struct ForkResolver {
+    chain_spec: Arc<ChainSpec>,
+}
+
+impl ForkResolver {
+    pub fn new(chain_spec: Arc<ChainSpec>) -> Self {
+        Self { chain_spec }
+    }
+
+    /// The `new_payload`/`fork_choice_updated` message version to use for a block built at
+    /// `timestamp`.
+    pub fn message_version(&self, timestamp: u64) -> EngineApiMessageVersion {
+        if self.chain_spec.is_cancun_active_at_timestamp(timestamp) {
+            EngineApiMessageVersion::V3
+        } else if self.chain_spec.is_shanghai_active_at_timestamp(timestamp) {
+            EngineApiMessageVersion::V2
+        } else {
+            EngineApiMessageVersion::V1
+        }
+    }
+
+    pub fn is_shanghai_active(&self, timestamp: u64) -> bool {
+        self.chain_spec.is_shanghai_active_at_timestamp(timestamp)
+    }
+
+    pub fn is_cancun_active(&self, timestamp: u64) -> bool {
+        self.chain_spec.is_cancun_active_at_timestamp(timestamp)
+    }
+}
+
+/// Wraps a [`PayloadAttributesBuilder`], filling in `withdrawals` once Shanghai is active and
+/// `parent_beacon_block_root` once Cancun is active, in case `inner` leaves them unset.
+#[derive(Debug)]
+pub struct ForkAwarePayloadAttributesBuilder<B> {
+    inner: B,
+    fork_resolver: ForkResolver,
+    withdrawals: Vec<Withdrawal>,
+}
+
+impl<B> ForkAwarePayloadAttributesBuilder<B> {
+    pub fn new(inner: B, fork_resolver: ForkResolver) -> Self {
+        Self { inner, fork_resolver, withdrawals: Vec::new() }
+    }
+
+    /// Sets the withdrawals to include once Shanghai activates. Empty by default.
+    pub fn with_withdrawals(mut self, withdrawals: Vec<Withdrawal>) -> Self {
+        self.withdrawals = withdrawals;
+        self
+    }
+}
+
+impl<B> PayloadAttributesBuilder<EthPayloadAttributes> for ForkAwarePayloadAttributesBuilder<B>
+where
+    B: PayloadAttributesBuilder<EthPayloadAttributes>,
+{
+    fn build(&self, timestamp: u64) -> EthPayloadAttributes {
+        let mut attrs = self.inner.build(timestamp);
+
+        if self.fork_resolver.is_shanghai_active(timestamp) && attrs.withdrawals.is_none() {
+            attrs.withdrawals = Some(self.withdrawals.clone());
+        }
+        if self.fork_resolver.is_cancun_active(timestamp) {
+            attrs.parent_beacon_block_root.get_or_insert(B256::ZERO);
+        }
+
+        attrs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reth_ethereum::chainspec::ChainSpecBuilder;
+
+    // Mainnet's well-known Shanghai/Cancun activation timestamps, used so these tests don't need
+    // to hand-build a custom genesis with its own hardfork schedule.
+    const SHANGHAI_TIME: u64 = 1681338455;
+    const CANCUN_TIME: u64 = 1710338135;
+
+    fn resolver() -> ForkResolver {
+        ForkResolver::new(Arc::new(ChainSpecBuilder::mainnet().build()))
+    }
+
+    /// A stub inner builder that returns a fixed set of attributes, so tests can control exactly
+    /// what `ForkAwarePayloadAttributesBuilder` does and doesn't fill in.
+    #[derive(Clone)]
+    struct StubAttributesBuilder(EthPayloadAttributes);
+
+    impl PayloadAttributesBuilder<EthPayloadAttributes> for StubAttributesBuilder {
+        fn build(&self, _timestamp: u64) -> EthPayloadAttributes {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn message_version_before_shanghai_is_v1() {
+        let resolver = resolver();
+        assert_eq!(resolver.message_version(SHANGHAI_TIME - 1), EngineApiMessageVersion::V1);
+    }
+
+    #[test]
+    fn message_version_at_shanghai_is_v2() {
+        let resolver = resolver();
+        assert_eq!(resolver.message_version(SHANGHAI_TIME), EngineApiMessageVersion::V2);
+        assert_eq!(resolver.message_version(CANCUN_TIME - 1), EngineApiMessageVersion::V2);
+    }
+
+    #[test]
+    fn message_version_at_cancun_is_v3() {
+        let resolver = resolver();
+        assert_eq!(resolver.message_version(CANCUN_TIME), EngineApiMessageVersion::V3);
+    }
+
+    #[test]
+    fn builder_leaves_withdrawals_unset_before_shanghai() {
+        let builder = ForkAwarePayloadAttributesBuilder::new(
+            StubAttributesBuilder(EthPayloadAttributes::default()),
+            resolver(),
+        );
+
+        let attrs = builder.build(SHANGHAI_TIME - 1);
+
+        assert_eq!(attrs.withdrawals, None);
+    }
+
+    #[test]
+    fn builder_defaults_withdrawals_once_shanghai_activates() {
+        let builder = ForkAwarePayloadAttributesBuilder::new(
+            StubAttributesBuilder(EthPayloadAttributes::default()),
+            resolver(),
+        )
+        .with_withdrawals(vec![Withdrawal::default()]);
+
+        let attrs = builder.build(SHANGHAI_TIME);
+
+        assert_eq!(attrs.withdrawals, Some(vec![Withdrawal::default()]));
+    }
+
+    #[test]
+    fn builder_does_not_override_inner_withdrawals() {
+        let mut inner_attrs = EthPayloadAttributes::default();
+        inner_attrs.withdrawals = Some(vec![Withdrawal::default()]);
+        let builder = ForkAwarePayloadAttributesBuilder::new(
+            StubAttributesBuilder(inner_attrs),
+            resolver(),
+        )
+        .with_withdrawals(vec![Withdrawal::default(), Withdrawal::default()]);
+
+        let attrs = builder.build(SHANGHAI_TIME);
+
+        assert_eq!(attrs.withdrawals, Some(vec![Withdrawal::default()]));
+    }
+
+    #[test]
+    fn builder_leaves_parent_beacon_root_unset_before_cancun() {
+        let builder = ForkAwarePayloadAttributesBuilder::new(
+            StubAttributesBuilder(EthPayloadAttributes::default()),
+            resolver(),
+        );
+
+        let attrs = builder.build(CANCUN_TIME - 1);
+
+        assert_eq!(attrs.parent_beacon_block_root, None);
+    }
+
+    #[test]
+    fn builder_defaults_parent_beacon_root_once_cancun_activates() {
+        let builder = ForkAwarePayloadAttributesBuilder::new(
+            StubAttributesBuilder(EthPayloadAttributes::default()),
+            resolver(),
+        );
+
+        let attrs = builder.build(CANCUN_TIME);
+
+        assert_eq!(attrs.parent_beacon_block_root, Some(B256::ZERO));
+    }
+}
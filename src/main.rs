@@ -1,11 +1,22 @@
+use alloy_primitives::Address;
 use alloy_provider::{network::AnyNetwork, ProviderBuilder};
 use reth_ethereum::{
-    cli::{chainspec::EthereumChainSpecParser, Cli}, engine::local::LocalPayloadAttributesBuilder, node::{builder::NodeHandle, EthereumNode}
+    cli::{chainspec::EthereumChainSpecParser, Cli}, engine::local::LocalPayloadAttributesBuilder, node::{builder::NodeHandle, EthereumAddOns, EthereumNode}
 };
+use secp256k1::SecretKey;
 use tracing::info;
 
-use crate::{poa_follower::PoaFollower, poa_miner::PoaMiner};
+use crate::{
+    authority::AuthoritySet,
+    consensus::PoaConsensusBuilder,
+    fork::ForkResolver,
+    poa_follower::PoaFollower,
+    poa_miner::{MiningMode, PoaMiner},
+};
 
+mod authority;
+mod consensus;
+mod fork;
 mod poa_miner;
 mod poa_follower;
 
@@ -20,6 +31,16 @@ pub struct PoaExampleArgs {
     /// Where to write the enode of this node.
     #[arg(long)]
     pub enode_file: Option<String>,
+
+    /// Addresses of the authorities allowed to seal blocks, in round-robin order. Shared by
+    /// every node in the network.
+    #[arg(long, value_delimiter = ',')]
+    pub authorities: Vec<Address>,
+
+    /// This node's authority signing key, as hex. Required to run as a producer; ignored by
+    /// followers, which only need `--authorities` to verify incoming blocks.
+    #[arg(long)]
+    pub signer_key: Option<String>,
 }
 
 fn main() -> eyre::Result<()> {
@@ -30,15 +51,27 @@ fn main() -> eyre::Result<()> {
     Cli::<EthereumChainSpecParser, PoaExampleArgs>::parse()
         .run(|builder, args| async move {
             info!(target: "reth::cli", "Launching node");
-            let NodeHandle { node, node_exit_future } =
-                builder.node(EthereumNode::default()).launch().await?;
-            
+            // The stock Ethereum consensus rejects our 97-byte Clique-style `extra_data`
+            // (32-byte vanity + 65-byte signature) as exceeding the 32-byte post-merge maximum,
+            // so swap in `PoaConsensusBuilder` rather than `EthereumNode::default()`'s components.
+            let NodeHandle { node, node_exit_future } = builder
+                .with_types::<EthereumNode>()
+                .with_components(
+                    EthereumNode::components().consensus(PoaConsensusBuilder::default()),
+                )
+                .with_add_ons(EthereumAddOns::default())
+                .launch()
+                .await?;
+
             // Add this enode to --trusted-peers on the follower.
             if let Some(enode_file) = &args.enode_file {
                 let enode = reth_ethereum::network::PeersInfo::local_node_record(&node.network);
                 std::fs::write(enode_file, enode.to_string())?;
             }
 
+            let authorities = AuthoritySet::new(args.authorities.clone());
+            let fork_resolver = ForkResolver::new(std::sync::Arc::new(node.config.chain.clone()));
+
             node.task_executor.spawn_critical("local engine", async move {
                 let beacon_engine_handle = node.add_ons_handle.beacon_engine_handle.clone();
                 if let Some(producer_url) = args.producer_url.as_ref() {
@@ -48,7 +81,9 @@ fn main() -> eyre::Result<()> {
                     PoaFollower::<_, AnyNetwork>::new(
                         beacon_engine_handle,
                         producer_url.clone(),
-                        provider
+                        provider,
+                        authorities,
+                        fork_resolver,
                     )
                     .run()
                     .await
@@ -57,20 +92,39 @@ fn main() -> eyre::Result<()> {
                     let provider = node.provider.clone();
                     let chain_spec = node.config.chain.clone();
                     let payload_builder_handle = node.payload_builder_handle.clone();
-                    let local_payload_attributes_builder = LocalPayloadAttributesBuilder::new(std::sync::Arc::new(chain_spec.clone()));
+                    let local_payload_attributes_builder = crate::fork::ForkAwarePayloadAttributesBuilder::new(
+                        LocalPayloadAttributesBuilder::new(std::sync::Arc::new(chain_spec.clone())),
+                        fork_resolver.clone(),
+                    );
+
+                    let signing_key = {
+                        let raw = args
+                            .signer_key
+                            .as_ref()
+                            .expect("--signer-key is required to run as a producer");
+                        SecretKey::from_slice(&alloy_primitives::hex::decode(raw).expect("signer key must be hex"))
+                            .expect("invalid signer key")
+                    };
+                    let signer = authority::address_from_secret_key(&signing_key);
 
                     PoaMiner::new(
                         provider,
                         local_payload_attributes_builder,
                         beacon_engine_handle,
-                        5,
+                        MiningMode::IntervalWithTransactions(std::time::Duration::from_secs(5)),
+                        std::time::Duration::from_secs(1),
                         payload_builder_handle,
+                        node.pool.clone(),
+                        authorities,
+                        signing_key,
+                        signer,
+                        fork_resolver,
                     )
                     .run()
                     .await
                 }
             });
-    
+
             node_exit_future.await
         })
         .unwrap();
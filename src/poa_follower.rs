@@ -1,11 +1,52 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 
+use alloy_primitives::{Address, B256};
 use futures_util::StreamExt;
-use reth_ethereum::{node::{api::{BuiltPayload, ConsensusEngineHandle, EngineApiMessageVersion, ExecutionPayload, PayloadTypes}}, primitives::{AlloyBlockHeader, NodePrimitives, SealedBlock}, rpc::types::engine::ForkchoiceState};
+use reth_ethereum::{node::{api::{BuiltPayload, ConsensusEngineHandle, EngineApiMessageVersion, ExecutionPayload, PayloadTypes}}, primitives::{AlloyBlockHeader, NodePrimitives, SealedBlock}, rpc::types::{engine::{ForkchoiceState, PayloadStatusEnum}, BlockTransactions}};
+use alloy_network::primitives::BlockResponse;
 use alloy_provider::{Network, Provider};
+use schnellru::{ByLength, LruMap};
 use tracing::{info, warn};
-use serde_json::Value;
 
+use crate::authority::{self, AuthoritySet};
+use crate::fork::ForkResolver;
+
+/// Initial delay between reconnection attempts.
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+/// Cap on the reconnection backoff, reached after repeated failures.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Number of decoded, seal-verified blocks kept in [`PoaFollower::block_cache`].
+const BLOCK_CACHE_SIZE: u32 = 16;
+/// Number of applied-block statuses kept in [`PoaFollower::applied`].
+const APPLIED_CACHE_SIZE: u32 = 256;
+/// Number of `(hash, signer)` pairs kept in [`PoaFollower::applied_blocks`], matching the window
+/// `sealed` retains so a block that ages out of `block_cache` is still recognized as applied.
+const APPLIED_BLOCKS_SIZE: u32 = 64;
+
+/// A decoded, seal-verified block, cached by block number so a backfill that re-encounters it
+/// (after a previous attempt was interrupted, or because it also arrived via the live
+/// subscription) can reuse it instead of re-fetching and re-verifying.
+struct CachedBlock<T: PayloadTypes> {
+    hash: B256,
+    signer: Address,
+    message_version: EngineApiMessageVersion,
+    payload: T::ExecutionData,
+}
+
+impl<T: PayloadTypes> Clone for CachedBlock<T>
+where
+    T::ExecutionData: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            hash: self.hash,
+            signer: self.signer,
+            message_version: self.message_version,
+            payload: self.payload.clone(),
+        }
+    }
+}
 
 pub struct PoaFollower<T: PayloadTypes, N: Network> {
     /// Sender for events to engine.
@@ -15,30 +56,89 @@ pub struct PoaFollower<T: PayloadTypes, N: Network> {
     producer_url: String,
 
     provider: Arc<dyn Provider<N>>,
+
+    /// The authorities allowed to seal blocks.
+    authorities: AuthoritySet,
+
+    /// Signers of the last `authorities.signer_cooldown()` accepted blocks, oldest first. Used
+    /// to reject a signer sealing again before its cooldown has elapsed.
+    recent_signers: VecDeque<Address>,
+
+    /// Number of the last block successfully applied to the engine. `None` until the first
+    /// block has been applied. Used to backfill any blocks missed while disconnected.
+    last_applied: Option<u64>,
+
+    /// Resolves which Engine API message version applies to a given block timestamp.
+    fork_resolver: ForkResolver,
+
+    /// Recently applied blocks, oldest first, as `(hash, signer)` pairs. Used to derive
+    /// signer-confirmation-based safe/finalized blocks, mirroring [`crate::poa_miner::PoaMiner`].
+    sealed: VecDeque<(B256, Address)>,
+
+    /// Bounded cache of decoded, seal-verified blocks, keyed by block number.
+    block_cache: LruMap<u64, CachedBlock<T>>,
+
+    /// Status the engine last returned for a block's `new_payload` call, keyed by block hash.
+    /// Lets a repeat delivery of an already-valid block (from reconnection or backfill) skip
+    /// re-submitting it to the engine entirely.
+    applied: LruMap<B256, PayloadStatusEnum>,
+
+    /// Hash and signer of every block whose seal has been verified and that has been handed to
+    /// the engine, keyed by block number, whatever status the engine returned for it. Checked
+    /// first in `apply_block_by_number`: a redelivered block found here is skipped without being
+    /// re-fetched, re-decoded or passed through `verify_seal` (and its cooldown bookkeeping)
+    /// again, even once it's aged out of `block_cache`.
+    applied_blocks: LruMap<u64, (B256, Address)>,
 }
 
-/// Recursively rename "uncles" fields to "ommers" in JSON data
-fn rename_uncles_to_ommers(mut value: Value) -> Value {
-    match &mut value {
-        Value::Object(map) => {
-            // Check if there's an "uncles" field and rename it to "ommers"
-            if let Some(uncles_value) = map.remove("uncles") {
-                map.insert("ommers".to_string(), uncles_value);
-            }
-            
-            // Recursively process nested objects
-            for (_, v) in map.iter_mut() {
-                *v = rename_uncles_to_ommers(v.clone());
-            }
-        }
-        Value::Array(arr) => {
-            for item in arr.iter_mut() {
-                *item = rename_uncles_to_ommers(item.clone());
-            }
-        }
-        _ => {}
-    }
-    value
+/// Builds a block body's JSON representation from its typed transactions and withdrawals,
+/// setting `ommers` explicitly rather than deriving it from the response: this chain is
+/// post-merge, so ommers are always empty and there's nothing to read off the wire for that
+/// field in the first place. Returns `Err` if `transactions` isn't the `Full` variant, which is
+/// what `fetch_and_verify_block` always requests.
+fn body_json(
+    transactions: &BlockTransactions<impl serde::Serialize>,
+    withdrawals: impl serde::Serialize,
+) -> eyre::Result<serde_json::Value> {
+    let BlockTransactions::Full(transactions) = transactions else {
+        eyre::bail!("block response did not include full transactions");
+    };
+
+    Ok(serde_json::json!({
+        "transactions": transactions,
+        "ommers": [] as [(); 0],
+        "withdrawals": withdrawals,
+    }))
+}
+
+/// Converts a producer's RPC block response into this node's header/body types.
+///
+/// Builds the header and body from the response's typed `header()`/`transactions()`/
+/// `withdrawals()` accessors (via `alloy_network`'s [`BlockResponse`] trait), rather than
+/// serializing the whole response to a `serde_json::Value` and doing field-name string surgery
+/// on it. Returns `Err` instead of panicking when the response doesn't match the shape this node
+/// expects, e.g. a non-full transactions variant or an unsupported transaction envelope.
+fn decode_block<B: reth_ethereum::primitives::Block, Resp: BlockResponse>(
+    block_response: Resp,
+) -> eyre::Result<(B::Header, B::Body)>
+where
+    B::Header: serde::de::DeserializeOwned,
+    B::Body: serde::de::DeserializeOwned,
+    Resp::Header: serde::Serialize,
+    Resp::Transaction: serde::Serialize,
+{
+    let header = serde_json::to_value(block_response.header())
+        .map_err(|e| eyre::eyre!("failed to serialize block header: {e}"))
+        .and_then(|json| {
+            serde_json::from_value::<B::Header>(json)
+                .map_err(|e| eyre::eyre!("failed to decode block header: {e}"))
+        })?;
+
+    let body = body_json(block_response.transactions(), block_response.withdrawals())?;
+    let body = serde_json::from_value::<B::Body>(body)
+        .map_err(|e| eyre::eyre!("failed to decode block body: {e}"))?;
+
+    Ok((header, body))
 }
 
 impl<B : reth_ethereum::primitives::Block, T: PayloadTypes, N: Network> PoaFollower<T, N>
@@ -46,77 +146,242 @@ where
     T: PayloadTypes<BuiltPayload: BuiltPayload<Primitives: NodePrimitives<Block = B>>>,
 
 {
-    pub fn new(to_engine: ConsensusEngineHandle<T>, producer_url: String, provider: Arc<dyn Provider<N>>) -> Self {
-        Self { to_engine, producer_url, provider }
+    pub fn new(
+        to_engine: ConsensusEngineHandle<T>,
+        producer_url: String,
+        provider: Arc<dyn Provider<N>>,
+        authorities: AuthoritySet,
+        fork_resolver: ForkResolver,
+    ) -> Self {
+        Self {
+            to_engine,
+            producer_url,
+            provider,
+            authorities,
+            recent_signers: VecDeque::new(),
+            last_applied: None,
+            fork_resolver,
+            sealed: VecDeque::new(),
+            block_cache: LruMap::new(ByLength::new(BLOCK_CACHE_SIZE)),
+            applied: LruMap::new(ByLength::new(APPLIED_CACHE_SIZE)),
+            applied_blocks: LruMap::new(ByLength::new(APPLIED_BLOCKS_SIZE)),
+        }
     }
 
+    /// Verifies that `header` was sealed by an authority in good standing: recovers the signer,
+    /// checks it belongs to the authority set, and checks it hasn't sealed within the last
+    /// `floor(N/2)` blocks.
+    fn verify_seal(&mut self, header: &B::Header) -> eyre::Result<Address> {
+        let signer = authority::recover_signer(header)?;
 
-    pub async fn run(self) {
+        eyre::ensure!(
+            self.authorities.contains(&signer),
+            "signer {signer} is not a member of the authority set"
+        );
+        eyre::ensure!(
+            !self.recent_signers.contains(&signer),
+            "signer {signer} sealed a block within the cooldown window"
+        );
 
-        let mut stream = match self.provider.subscribe_blocks().await {
-            Ok(sub) => sub.into_stream(),
-            Err(err) => {
-                warn!(
-                    target: "consensus::debug-client",
-                    %err,
-                    url=%self.producer_url,
-                    "Failed to subscribe to blocks",
-                );
-                return;
-            }
-        };
-        while let Some(header) = stream.next().await {
-            info!("block {}", header.number());
-            let block = self
-                .provider
-                .get_block_by_number(header.number().into())
-                .full()
-                .await.unwrap()
-                .ok_or_else(|| eyre::eyre!("block not found by number {}", header.number()));
-
-            match block {
-                Ok(block_response) => {
-                    let json = serde_json::to_value(block_response)
-                        .expect("Block serialization cannot fail");
-
-                    // Rename "uncles" fields to "ommers" in the JSON
-                    let json = rename_uncles_to_ommers(json);
-
-                    info!("json: {}", json);
-                    let header = serde_json::from_value::<B::Header>(json.clone())
-                        .expect("Header deserialization cannot fail");
-                    let body = serde_json::from_value::<B::Body>(json)
-                        .expect("Body deserialization cannot fail");
-                    let pblock = B::new(header, body);
-
-                    let payload = T::block_to_payload(SealedBlock::new_unhashed(pblock));
-
-                    let hash = payload.block_hash();
-                    
-                    println!("{payload:?}");
-                    let _ = self.to_engine.new_payload(payload).await;
-
-                    let fcu = ForkchoiceState {
-                        head_block_hash: hash,
-                        safe_block_hash: hash,
-                        finalized_block_hash: hash,
-                    };
+        self.recent_signers.push_back(signer);
+        if self.recent_signers.len() > self.authorities.signer_cooldown() {
+            self.recent_signers.pop_front();
+        }
+
+        Ok(signer)
+    }
+
+
+    /// Subscribes to the producer and relays blocks to the engine forever, reconnecting with
+    /// exponential backoff (capped at [`MAX_RECONNECT_BACKOFF`]) whenever the subscription ends
+    /// or fails to establish, and backfilling any blocks missed during the outage.
+    pub async fn run(mut self) {
+        let mut backoff = INITIAL_RECONNECT_BACKOFF;
 
-                    let _ = self.to_engine.fork_choice_updated(fcu, None, EngineApiMessageVersion::default()).await;
+        loop {
+            match self.provider.subscribe_blocks().await {
+                Ok(sub) => {
+                    backoff = INITIAL_RECONNECT_BACKOFF;
 
+                    if let Err(err) = self.backfill().await {
+                        warn!(
+                            target: "consensus::debug-client",
+                            %err,
+                            url=%self.producer_url,
+                            "Failed to backfill blocks missed while disconnected",
+                        );
+                    }
+
+                    let mut stream = sub.into_stream();
+                    while let Some(header) = stream.next().await {
+                        if let Err(err) = self.apply_block_by_number(header.number()).await {
+                            warn!(
+                                target: "consensus::debug-client",
+                                %err,
+                                url=%self.producer_url,
+                                "Failed to apply a block",
+                            );
+                        }
+                    }
+
+                    warn!(
+                        target: "consensus::debug-client",
+                        url=%self.producer_url,
+                        "Block subscription ended; reconnecting",
+                    );
                 }
                 Err(err) => {
                     warn!(
                         target: "consensus::debug-client",
                         %err,
                         url=%self.producer_url,
-                        "Failed to fetch a block",
+                        "Failed to subscribe to blocks; retrying",
                     );
                 }
             }
+
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, MAX_RECONNECT_BACKOFF);
         }
     }
 
+    /// Walks from `last_applied + 1` up to the producer's current head, applying any blocks
+    /// that were missed while disconnected. A no-op on the very first connection.
+    async fn backfill(&mut self) -> eyre::Result<()> {
+        let Some(last_applied) = self.last_applied else {
+            return Ok(());
+        };
+
+        let head = self.provider.get_block_number().await?;
+        for number in (last_applied + 1)..=head {
+            self.apply_block_by_number(number).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Fetches (or reuses a cached decode of) the block at `number`, verifies and applies it,
+    /// then advances `last_applied`. Idempotent: a block already submitted to the engine with a
+    /// valid status is not submitted again, so repeat deliveries under at-least-once delivery
+    /// (reconnection, backfill) are harmless.
+    async fn apply_block_by_number(&mut self, number: u64) -> eyre::Result<()> {
+        if self.applied_blocks.get(&number).is_some() {
+            // Already decoded, seal-verified (and cooldown-accounted) and submitted in a
+            // previous cycle. `block_cache` alone isn't enough to catch this once the block has
+            // aged out of its 16-entry window; without this check a redelivery (reconnection,
+            // backfill overlap) would re-run `verify_seal` and double-count the signer's
+            // cooldown.
+            info!("block {} (already applied)", number);
+            self.last_applied = Some(number);
+            return Ok(());
+        }
+
+        let CachedBlock { hash, signer, message_version, payload } =
+            match self.block_cache.get(&number).cloned() {
+                Some(cached) => {
+                    info!("block {} (cached)", number);
+                    cached
+                }
+                None => {
+                    info!("block {}", number);
+                    let Some(cached) = self.fetch_and_verify_block(number).await? else {
+                        // Decode or seal verification failed; already logged and skipped.
+                        self.last_applied = Some(number);
+                        return Ok(());
+                    };
+                    self.block_cache.insert(number, cached.clone());
+                    cached
+                }
+            };
+        self.applied_blocks.insert(number, (hash, signer));
+
+        let already_valid = matches!(
+            self.applied.get(&hash),
+            Some(PayloadStatusEnum::Valid)
+        );
+
+        if !already_valid {
+            let status = self
+                .to_engine
+                .new_payload(payload)
+                .await
+                .map(|status| status.status)
+                .unwrap_or(PayloadStatusEnum::Syncing);
+            let is_valid = matches!(status, PayloadStatusEnum::Valid);
+            self.applied.insert(hash, status);
+
+            // Only a block the engine actually accepted may count toward confirmations or be
+            // pointed at as head/safe/finalized; `Invalid`/`Syncing` must not advance finality,
+            // mirroring the guard `PoaMiner::advance` applies to its own seals.
+            if is_valid {
+                self.sealed.push_back((hash, signer));
+                if self.sealed.len() > 64 {
+                    self.sealed.pop_front();
+                }
+                let (head, safe, finalized) = self.authorities.finality_state(&self.sealed);
+
+                let fcu = ForkchoiceState {
+                    head_block_hash: head,
+                    safe_block_hash: safe,
+                    finalized_block_hash: finalized,
+                };
+                let _ = self
+                    .to_engine
+                    .fork_choice_updated(fcu, None, message_version)
+                    .await;
+            }
+        }
+
+        self.last_applied = Some(number);
+        Ok(())
+    }
+
+    /// Fetches the block at `number`, decodes it and verifies its seal. Returns `Ok(None)` (after
+    /// logging) if the block's shape is unexpected or its seal doesn't check out, rather than
+    /// treating either as fatal to the relay loop.
+    async fn fetch_and_verify_block(&mut self, number: u64) -> eyre::Result<Option<CachedBlock<T>>> {
+        let block_response = self
+            .provider
+            .get_block_by_number(number.into())
+            .full()
+            .await?
+            .ok_or_else(|| eyre::eyre!("block not found by number {}", number))?;
+
+        let (header, body) = match decode_block::<B, _>(block_response) {
+            Ok(decoded) => decoded,
+            Err(err) => {
+                warn!(
+                    target: "consensus::debug-client",
+                    %err,
+                    number,
+                    "Skipping block with an unexpected shape",
+                );
+                return Ok(None);
+            }
+        };
+
+        let signer = match self.verify_seal(&header) {
+            Ok(signer) => signer,
+            Err(err) => {
+                warn!(
+                    target: "consensus::debug-client",
+                    %err,
+                    number,
+                    "Rejecting block with invalid authority seal",
+                );
+                return Ok(None);
+            }
+        };
+
+        let message_version = self.fork_resolver.message_version(header.timestamp());
+
+        let pblock = B::new(header, body);
+        let payload = T::block_to_payload(SealedBlock::new_unhashed(pblock));
+        let hash = payload.block_hash();
+
+        Ok(Some(CachedBlock { hash, signer, message_version, payload }))
+    }
+
     // async fn get_block(&self, block_number: u64) -> eyre::Result<N::BlockResponse> {
     //     let block = self
     //         .provider
@@ -135,91 +400,21 @@ mod tests {
     use serde_json::json;
 
     #[test]
-    fn test_rename_uncles_to_ommers() {
-        // Test basic rename
-        let input = json!({
-            "number": "0x1",
-            "hash": "0x123",
-            "uncles": ["0xabc", "0xdef"]
-        });
-        
-        let result = rename_uncles_to_ommers(input);
-        
-        assert!(result.get("ommers").is_some());
-        assert!(result.get("uncles").is_none());
-        assert_eq!(result["ommers"], json!(["0xabc", "0xdef"]));
-    }
+    fn body_json_sets_ommers_empty_and_keeps_transactions() {
+        let transactions = BlockTransactions::Full(vec![json!({"hash": "0x1"})]);
 
-    #[test]
-    fn test_rename_uncles_nested() {
-        // Test nested rename
-        let input = json!({
-            "header": {
-                "number": "0x1",
-                "uncles": ["0x111"]
-            },
-            "body": {
-                "transactions": [],
-                "uncles": ["0x222", "0x333"]
-            },
-            "uncles": ["0x444"]
-        });
-        
-        let result = rename_uncles_to_ommers(input);
-        
-        // Check top level
-        assert!(result.get("ommers").is_some());
-        assert!(result.get("uncles").is_none());
-        assert_eq!(result["ommers"], json!(["0x444"]));
-        
-        // Check nested in header
-        assert!(result["header"].get("ommers").is_some());
-        assert!(result["header"].get("uncles").is_none());
-        assert_eq!(result["header"]["ommers"], json!(["0x111"]));
-        
-        // Check nested in body
-        assert!(result["body"].get("ommers").is_some());
-        assert!(result["body"].get("uncles").is_none());
-        assert_eq!(result["body"]["ommers"], json!(["0x222", "0x333"]));
-    }
+        let body = body_json(&transactions, Option::<()>::None).unwrap();
 
-    #[test]
-    fn test_rename_uncles_array() {
-        // Test rename in arrays
-        let input = json!([
-            {
-                "number": "0x1",
-                "uncles": ["0xaaa"]
-            },
-            {
-                "number": "0x2",
-                "uncles": ["0xbbb"]
-            }
-        ]);
-        
-        let result = rename_uncles_to_ommers(input);
-        
-        let array = result.as_array().unwrap();
-        assert!(array[0].get("ommers").is_some());
-        assert!(array[0].get("uncles").is_none());
-        assert!(array[1].get("ommers").is_some());
-        assert!(array[1].get("uncles").is_none());
+        assert_eq!(body["ommers"], json!([]));
+        assert_eq!(body["transactions"], json!([{"hash": "0x1"}]));
     }
 
     #[test]
-    fn test_no_uncles_field() {
-        // Test that objects without uncles field are unchanged
-        let input = json!({
-            "number": "0x1",
-            "hash": "0x123",
-            "transactions": []
-        });
-        
-        let expected = input.clone();
-        let result = rename_uncles_to_ommers(input);
-        
-        assert_eq!(result, expected);
+    fn body_json_rejects_non_full_transactions() {
+        let transactions: BlockTransactions<serde_json::Value> =
+            BlockTransactions::Hashes(vec![B256::ZERO]);
+
+        assert!(body_json(&transactions, Option::<()>::None).is_err());
     }
 }
 
-
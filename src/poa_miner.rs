@@ -5,57 +5,113 @@ use std::{
     time::{Duration, UNIX_EPOCH},
 };
 
-use alloy_primitives::B256;
+use alloy_primitives::{Address, B256, U256};
 use eyre::OptionExt;
 use reth_ethereum::{
-    node::api::{
-        BuiltPayload, ConsensusEngineHandle, EngineApiMessageVersion, PayloadAttributesBuilder,
-        PayloadTypes,
-    },
+    node::api::{BuiltPayload, ConsensusEngineHandle, PayloadAttributesBuilder, PayloadTypes},
+    pool::TransactionPool,
+    primitives::{Block, Header, NodePrimitives, SealedBlock},
     rpc::types::engine::ForkchoiceState,
     storage::BlockReader,
 };
 use reth_payload_builder::{PayloadBuilderHandle, PayloadKind};
+use secp256k1::SecretKey;
 use tracing::{error, info};
 
+use crate::authority::{self, AuthoritySet};
+use crate::fork::ForkResolver;
+
+/// Controls when [`PoaMiner`] seals a new block.
+///
+/// This mirrors the intent of the upstream `MiningMode` used by `LocalMiner`, minus the
+/// auto-mining-on-RPC-call variant which doesn't apply to a standalone PoA producer.
+#[derive(Debug, Clone, Copy)]
+pub enum MiningMode {
+    /// Seal a new block every `interval`, regardless of whether the pool has pending
+    /// transactions.
+    Interval(Duration),
+    /// Seal a new block as soon as a transaction arrives in the pool, subject to
+    /// `min_interval` debouncing.
+    Instant,
+    /// Seal every `interval` like [`MiningMode::Interval`], but also wake early when a
+    /// transaction arrives, and skip sealing entirely if the pool is empty.
+    IntervalWithTransactions(Duration),
+}
+
 #[derive(Debug)]
-pub struct PoaMiner<T: PayloadTypes, B> {
+pub struct PoaMiner<T: PayloadTypes, B, Pool, P> {
     /// The payload attribute builder for the engine
     payload_attributes_builder: B,
     /// Sender for events to engine.
     to_engine: ConsensusEngineHandle<T>,
-    /// The block rate in seconds.
-    interval: u64,
+    /// When this miner seals blocks.
+    mode: MiningMode,
+    /// The minimum time between two sealed blocks, regardless of mode. Prevents transaction
+    /// arrivals from causing the chain to seal faster than this.
+    min_interval: Duration,
     /// The payload builder for the engine
     payload_builder: PayloadBuilderHandle<T>,
     /// Timestamp for the next block.
     last_timestamp: u64,
-    /// Stores latest mined blocks.
-    last_block_hashes: VecDeque<B256>,
+    /// Number of the highest block `sealed` (and the provider's live chain head) has been
+    /// resynced up to. Re-derived from the provider every cycle in [`Self::resync`], rather than
+    /// a counter this node only advances when it seals a block itself.
+    last_synced_block_number: u64,
+    /// Recently sealed blocks, oldest first, as `(hash, signer)` pairs. Used to derive
+    /// signer-confirmation-based safe/finalized blocks.
+    sealed: VecDeque<(B256, Address)>,
+    /// Transaction pool, used to wake block production on incoming transactions.
+    pool: Pool,
+    /// The authorities allowed to seal blocks, and the round-robin order between them.
+    authorities: AuthoritySet,
+    /// This node's signing key. Must correspond to an address in `authorities`.
+    signing_key: SecretKey,
+    /// This node's signer address, derived from `signing_key`.
+    signer_address: Address,
+    /// Resolves which Engine API message version applies to a given block timestamp.
+    fork_resolver: ForkResolver,
+    /// Chain state provider, used to resync the live chain head each cycle.
+    provider: P,
 }
 
-impl<T: PayloadTypes, B> PoaMiner<T, B>
+impl<Blk, T, B, Pool, P> PoaMiner<T, B, Pool, P>
 where
-    T: PayloadTypes,
+    Blk: Block<Header = Header>,
+    T: PayloadTypes<BuiltPayload: BuiltPayload<Primitives: NodePrimitives<Block = Blk>>>,
     B: PayloadAttributesBuilder<<T as PayloadTypes>::PayloadAttributes>,
+    Pool: TransactionPool,
+    P: BlockReader,
 {
     pub fn new(
-        provider: impl BlockReader,
+        provider: P,
         payload_attributes_builder: B,
         to_engine: ConsensusEngineHandle<T>,
-        interval: u64,
+        mode: MiningMode,
+        min_interval: Duration,
         payload_builder: PayloadBuilderHandle<T>,
+        pool: Pool,
+        authorities: AuthoritySet,
+        signing_key: SecretKey,
+        signer_address: Address,
+        fork_resolver: ForkResolver,
     ) -> Self {
-        info!(
-            "PoaMiner: Starting POA at block {}",
-            provider.best_block_number().unwrap()
+        assert!(
+            authorities.contains(&signer_address),
+            "local signer address is not a member of the authority set"
         );
 
+        let best_block_number = provider.best_block_number().unwrap();
+        info!("PoaMiner: Starting POA at block {}", best_block_number);
+
         let latest_header = provider
-            .sealed_header(provider.best_block_number().unwrap())
+            .sealed_header(best_block_number)
             .unwrap()
             .unwrap();
-        let last_block_hashes = VecDeque::from([latest_header.hash()]);
+        // We don't know who actually signed pre-existing blocks; assume the in-turn authority.
+        let sealed = VecDeque::from([(
+            latest_header.hash(),
+            authorities.in_turn_signer(best_block_number),
+        )]);
         let last_timestamp = std::time::SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .expect("cannot be earlier than UNIX_EPOCH")
@@ -63,23 +119,65 @@ where
         Self {
             payload_attributes_builder,
             to_engine,
-            interval,
+            mode,
+            min_interval,
             payload_builder,
             last_timestamp,
-            last_block_hashes,
+            last_synced_block_number: best_block_number,
+            sealed,
+            pool,
+            authorities,
+            signing_key,
+            signer_address,
+            fork_resolver,
+            provider,
+        }
+    }
+
+    /// Returns the interval on which `block_interval` should tick.
+    ///
+    /// `Instant` mode has no fixed cadence of its own, so it ticks on `min_interval` purely as
+    /// a backstop that also debounces transaction-triggered advances.
+    fn tick_interval(&self) -> Duration {
+        match self.mode {
+            MiningMode::Interval(interval) | MiningMode::IntervalWithTransactions(interval) => {
+                interval
+            }
+            MiningMode::Instant => self.min_interval,
         }
     }
 
     pub async fn run(mut self) {
         let mut fcu_interval = tokio::time::interval(Duration::from_secs(1));
-        let mut block_interval = tokio::time::interval(Duration::from_secs(self.interval));
+        let mut block_interval = tokio::time::interval(self.tick_interval());
+        let mut new_transactions = self.pool.pending_transactions_listener();
+        let mut last_advance = tokio::time::Instant::now();
         loop {
             tokio::select! {
-                // Wait for the interval or the pool to receive a transaction
-                // Note that this should be more like the original MiningMode.
+                // Wait for the interval...
                 _ = block_interval.tick() => {
+                    if self.should_skip_empty_block() {
+                        continue;
+                    }
+                    if last_advance.elapsed() < self.min_interval {
+                        continue;
+                    }
+                    if let Err(e) = self.advance().await {
+                        error!(target: "engine::local", "Error advancing the chain: {:?}", e);
+                    } else {
+                        last_advance = tokio::time::Instant::now();
+                    }
+                }
+                // ...or for the pool to receive a transaction, debounced by `min_interval` so we
+                // never seal faster than that regardless of how fast transactions arrive.
+                Some(_) = new_transactions.recv(), if self.wakes_on_transactions() => {
+                    if last_advance.elapsed() < self.min_interval {
+                        continue;
+                    }
                     if let Err(e) = self.advance().await {
                         error!(target: "engine::local", "Error advancing the chain: {:?}", e);
+                    } else {
+                        last_advance = tokio::time::Instant::now();
                     }
                 }
                 // send FCU once in a while
@@ -92,6 +190,21 @@ where
         }
     }
 
+    /// Whether this miner should wake early on incoming transactions.
+    fn wakes_on_transactions(&self) -> bool {
+        matches!(
+            self.mode,
+            MiningMode::Instant | MiningMode::IntervalWithTransactions(_)
+        )
+    }
+
+    /// In `Instant` and `IntervalWithTransactions` mode, don't bother sealing a block if the pool
+    /// is empty: both only seal in order to include pending transactions.
+    fn should_skip_empty_block(&self) -> bool {
+        matches!(self.mode, MiningMode::Instant | MiningMode::IntervalWithTransactions(_))
+            && self.pool.pool_size().pending == 0
+    }
+
     /// Sends a FCU to the engine.
     async fn update_forkchoice_state(&self) -> eyre::Result<()> {
         let res = self
@@ -99,7 +212,7 @@ where
             .fork_choice_updated(
                 self.forkchoice_state(),
                 None,
-                EngineApiMessageVersion::default(),
+                self.fork_resolver.message_version(self.last_timestamp),
             )
             .await?;
 
@@ -110,27 +223,55 @@ where
         Ok(())
     }
 
-    /// Returns current forkchoice state.
+    /// Returns current forkchoice state, with `safe`/`finalized` derived from authority
+    /// confirmations rather than a fixed block-count offset.
     fn forkchoice_state(&self) -> ForkchoiceState {
+        let (head, safe, finalized) = self.authorities.finality_state(&self.sealed);
         ForkchoiceState {
-            head_block_hash: *self
-                .last_block_hashes
-                .back()
-                .expect("at least 1 block exists"),
-            safe_block_hash: *self
-                .last_block_hashes
-                .get(self.last_block_hashes.len().saturating_sub(32))
-                .expect("at least 1 block exists"),
-            finalized_block_hash: *self
-                .last_block_hashes
-                .get(self.last_block_hashes.len().saturating_sub(64))
-                .expect("at least 1 block exists"),
+            head_block_hash: head,
+            safe_block_hash: safe,
+            finalized_block_hash: finalized,
         }
     }
 
+    /// Re-derives the next block number to seal from the provider's live chain head, backfilling
+    /// `sealed` with any blocks produced by other authorities since we last looked.
+    ///
+    /// A node is only in turn for one block out of every `authorities.len()`, so between its own
+    /// seals the chain head advances solely through blocks sealed by other authorities and
+    /// relayed in via devp2p sync. Without resyncing here, a node that isn't first in the
+    /// rotation would check the same stale block number forever, since it otherwise only
+    /// advances its notion of the next block number when it seals a block itself.
+    fn resync(&mut self) -> eyre::Result<u64> {
+        let best_block_number = self.provider.best_block_number()?;
+        for block_number in (self.last_synced_block_number + 1)..=best_block_number {
+            let sealed_header = self
+                .provider
+                .sealed_header(block_number)?
+                .ok_or_eyre("missing header for synced block")?;
+            // Recover the real signer rather than assuming the in-turn authority: an out-of-turn
+            // seal (lower difficulty, but still valid) would otherwise be mis-attributed, which
+            // corrupts the confirmation count `finality_state` relies on for safety.
+            let signer = authority::recover_signer(sealed_header.header())?;
+            self.sealed.push_back((sealed_header.hash(), signer));
+            if self.sealed.len() > 64 {
+                self.sealed.pop_front();
+            }
+        }
+        self.last_synced_block_number = best_block_number;
+
+        Ok(best_block_number + 1)
+    }
+
     /// Generates payload attributes for a new block, passes them to FCU and inserts built payload
-    /// through newPayload.
+    /// through newPayload. Only seals if it is this node's turn in the authority round.
     async fn advance(&mut self) -> eyre::Result<()> {
+        let block_number = self.resync()?;
+        if self.authorities.in_turn_signer(block_number) != self.signer_address {
+            // Not our turn to seal this block; wait for the round to come back around.
+            return Ok(());
+        }
+
         let timestamp = std::cmp::max(
             self.last_timestamp + 1,
             std::time::SystemTime::now()
@@ -144,7 +285,7 @@ where
             .fork_choice_updated(
                 self.forkchoice_state(),
                 Some(self.payload_attributes_builder.build(timestamp)),
-                EngineApiMessageVersion::default(),
+                self.fork_resolver.message_version(timestamp),
             )
             .await?;
 
@@ -162,9 +303,10 @@ where
             eyre::bail!("No payload")
         };
 
-        let block = payload.block();
+        let sealed_block = self.seal_block(payload.block().clone(), block_number);
+        let hash = sealed_block.hash();
 
-        let payload = T::block_to_payload(block.clone());
+        let payload = T::block_to_payload(sealed_block);
         let res = self.to_engine.new_payload(payload).await?;
 
         if !res.is_valid() {
@@ -172,12 +314,26 @@ where
         }
 
         self.last_timestamp = timestamp;
-        self.last_block_hashes.push_back(block.hash());
+        self.last_synced_block_number = block_number;
+        self.sealed.push_back((hash, self.signer_address));
         // ensure we keep at most 64 blocks
-        if self.last_block_hashes.len() > 64 {
-            self.last_block_hashes.pop_front();
+        if self.sealed.len() > 64 {
+            self.sealed.pop_front();
         }
 
         Ok(())
     }
+
+    /// Stamps the built block's header with the Clique-style difficulty and signature for
+    /// `block_number`, and re-seals it.
+    fn seal_block(&self, block: SealedBlock<Blk>, block_number: u64) -> SealedBlock<Blk> {
+        let (sealed_header, body) = block.split();
+        let mut header = sealed_header.into_header();
+
+        header.difficulty = U256::from(self.authorities.difficulty_for(block_number, &self.signer_address));
+        header.extra_data = authority::empty_extra_data(b"reth-poa-example");
+        authority::sign_header(&mut header, &self.signing_key);
+
+        SealedBlock::new_unhashed(Blk::new(header, body))
+    }
 }